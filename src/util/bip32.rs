@@ -0,0 +1,207 @@
+//! BIP32 key-derivation types.
+//!
+//! Child numbers, key fingerprints and derivation paths as defined in [BIP 32]. Extended public
+//! and private keys are not implemented here.
+//!
+//! [BIP 32]: https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+
+use std::error;
+use std::fmt;
+use std::ops::Deref;
+use std::slice;
+use std::str::FromStr;
+
+/// A child number for a derived key.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum ChildNumber {
+    /// A non-hardened child number.
+    Normal {
+        /// Non-hardened index.
+        index: u32,
+    },
+    /// A hardened child number.
+    Hardened {
+        /// Hardened index.
+        index: u32,
+    },
+}
+
+impl ChildNumber {
+    /// Returns `true` if the child number is a hardened one.
+    pub fn is_hardened(&self) -> bool {
+        match *self {
+            ChildNumber::Hardened { .. } => true,
+            ChildNumber::Normal { .. } => false,
+        }
+    }
+}
+
+impl From<u32> for ChildNumber {
+    fn from(number: u32) -> Self {
+        if number & (1 << 31) != 0 {
+            ChildNumber::Hardened { index: number ^ (1 << 31) }
+        } else {
+            ChildNumber::Normal { index: number }
+        }
+    }
+}
+
+impl From<ChildNumber> for u32 {
+    fn from(cnum: ChildNumber) -> Self {
+        match cnum {
+            ChildNumber::Normal { index } => index,
+            ChildNumber::Hardened { index } => index | (1 << 31),
+        }
+    }
+}
+
+impl FromStr for ChildNumber {
+    type Err = Error;
+
+    fn from_str(inp: &str) -> Result<ChildNumber, Error> {
+        let is_hardened = inp.chars().last().map_or(false, |l| l == '\'' || l == 'h');
+        let index = inp.trim_end_matches(|c| c == '\'' || c == 'h')
+            .parse::<u32>()
+            .map_err(|_| Error::InvalidChildNumberFormat)?;
+
+        if index & (1 << 31) != 0 {
+            return Err(Error::InvalidChildNumberFormat);
+        }
+
+        Ok(if is_hardened {
+            ChildNumber::Hardened { index }
+        } else {
+            ChildNumber::Normal { index }
+        })
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChildNumber::Hardened { index } => write!(f, "{}'", index),
+            ChildNumber::Normal { index } => write!(f, "{}", index),
+        }
+    }
+}
+
+/// A fingerprint, the first four bytes of the hash160 of an extended public key, as defined by
+/// BIP 32.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct Fingerprint([u8; 4]);
+
+impl Fingerprint {
+    /// Returns the byte representation of this fingerprint.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        self.0
+    }
+}
+
+impl<'a> From<&'a [u8]> for Fingerprint {
+    fn from(bytes: &'a [u8]) -> Self {
+        let mut fp = [0u8; 4];
+        fp.copy_from_slice(&bytes[0..4]);
+        Fingerprint(fp)
+    }
+}
+
+/// A BIP32 derivation path.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(numbers: Vec<ChildNumber>) -> Self {
+        DerivationPath(numbers)
+    }
+}
+
+impl From<DerivationPath> for Vec<ChildNumber> {
+    fn from(path: DerivationPath) -> Self {
+        path.0
+    }
+}
+
+impl<'a> From<&'a [ChildNumber]> for DerivationPath {
+    fn from(numbers: &'a [ChildNumber]) -> Self {
+        DerivationPath(numbers.to_vec())
+    }
+}
+
+impl ::std::iter::FromIterator<ChildNumber> for DerivationPath {
+    fn from_iter<T: IntoIterator<Item = ChildNumber>>(iter: T) -> Self {
+        DerivationPath(Vec::from_iter(iter))
+    }
+}
+
+impl<'a> ::std::iter::IntoIterator for &'a DerivationPath {
+    type Item = &'a ChildNumber;
+    type IntoIter = slice::Iter<'a, ChildNumber>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl AsRef<[ChildNumber]> for DerivationPath {
+    fn as_ref(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl Deref for DerivationPath {
+    type Target = [ChildNumber];
+
+    fn deref(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut parts = path.split('/');
+
+        // First part must be `m`.
+        if parts.next().unwrap() != "m" {
+            return Err(Error::InvalidDerivationPathFormat);
+        }
+
+        let path: Result<Vec<ChildNumber>, Error> = parts.map(str::parse).collect();
+        Ok(DerivationPath(path?))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for cn in self.0.iter() {
+            write!(f, "/{}", cn)?;
+        }
+        Ok(())
+    }
+}
+
+/// A fingerprint and derivation path from a master key down to the key that produced a given
+/// PSBT key-source value.
+pub type KeySource = (Fingerprint, DerivationPath);
+
+/// A BIP32 error.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A child number was out of range or malformed.
+    InvalidChildNumberFormat,
+    /// A derivation path string was malformed.
+    InvalidDerivationPathFormat,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidChildNumberFormat => write!(f, "invalid child number format"),
+            Error::InvalidDerivationPathFormat => write!(f, "invalid derivation path format"),
+        }
+    }
+}
+
+impl error::Error for Error {}
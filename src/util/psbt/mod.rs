@@ -0,0 +1,18 @@
+//! Partially Signed Bitcoin Transactions (PSBT).
+
+pub mod serialize;
+
+/// A key in a `PSBT_*_PROPRIETARY` key-value pair, as defined by BIP 174. Proprietary keys let
+/// applications and protocols (that are not part of the PSBT standard) stash arbitrary data in a
+/// PSBT without colliding with standard or other proprietary fields.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ProprietaryKey {
+    /// Identifies the application or protocol that defined this proprietary key, by convention a
+    /// short ASCII string.
+    pub prefix: Vec<u8>,
+    /// A subtype, scoped to `prefix`, distinguishing different proprietary fields used by the
+    /// same application.
+    pub subtype: u8,
+    /// Any additional key data beyond the prefix and subtype.
+    pub key: Vec<u8>,
+}
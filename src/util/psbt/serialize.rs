@@ -3,29 +3,56 @@
 //! Defines traits used for (de)serializing PSBT values into/from raw
 //! bytes in PSBT key-value pairs.
 
-use std::io::{self, Cursor};
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Write};
 
 use secp256k1::{PublicKey, Secp256k1};
 
 use blockdata::script::Script;
-use blockdata::transaction::Transaction;
-use consensus::encode::{self, serialize, Decodable};
-use util::bip32::{ChildNumber, Fingerprint};
+use blockdata::transaction::{SigHashType, Transaction, TxOut};
+use consensus::encode::{self, Decodable};
+use util::bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource};
+use util::psbt::ProprietaryKey;
+use util::schnorr::{SchnorrSig, SchnorrSigHashType, XOnlyPublicKey};
+use util::taproot::{
+    ControlBlock, LeafVersion, TapLeafHash, TAPROOT_CONTROL_BASE_SIZE,
+    TAPROOT_CONTROL_MAX_NODE_COUNT, TAPROOT_CONTROL_NODE_SIZE,
+};
 
 /// A trait for serializing a value as raw data for insertion into PSBT
 /// key-value pairs.
 pub trait Serialize {
     /// Serialize a value as raw data.
     fn serialize(&self) -> Vec<u8>;
+
+    /// Serialize a value directly into `writer`. The default implementation
+    /// serializes to a buffer and writes that; override it when a value can
+    /// be written without an intermediate allocation.
+    fn consensus_encode<W: io::Write>(&self, mut writer: W) -> Result<usize, io::Error> {
+        let buf = self.serialize();
+        writer.write_all(&buf)?;
+        Ok(buf.len())
+    }
 }
 
 /// A trait for deserializing a value from raw data in PSBT key-value pairs.
 pub trait Deserialize: Sized {
-    /// Deserialize a value from raw data.
+    /// Deserialize a value from raw data, operating directly on the borrowed slice with no
+    /// intermediate allocation.
     fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error>;
+
+    /// Deserialize a value by reading it to completion from `reader`. The default
+    /// implementation buffers the reader and delegates to [`Deserialize::deserialize`]; override
+    /// it when a value's wire size is known up front and can be read without buffering.
+    fn consensus_decode<R: io::Read>(mut reader: R) -> Result<Self, encode::Error> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Self::deserialize(&buf)
+    }
 }
 
 impl_psbt_de_serialize!(Transaction);
+impl_psbt_de_serialize!(TxOut);
 
 impl Serialize for Script {
     fn serialize(&self) -> Vec<u8> {
@@ -41,7 +68,7 @@ impl Deserialize for Script {
 
 impl Serialize for PublicKey {
     fn serialize(&self) -> Vec<u8> {
-        self.serialize().to_vec()
+        PublicKey::serialize(self).to_vec()
     }
 }
 
@@ -52,43 +79,447 @@ impl Deserialize for PublicKey {
     }
 }
 
-impl Serialize for (Fingerprint, Vec<ChildNumber>) {
+impl Serialize for SigHashType {
     fn serialize(&self) -> Vec<u8> {
-        let mut rv: Vec<u8> = Vec::with_capacity(4 + 4 * (&self.1).len());
+        encode::serialize(&self.as_u32())
+    }
+}
 
-        rv.append(&mut self.0.to_bytes().to_vec());
+/// Checks that `raw` round-trips through `SigHashType`, rejecting non-standard flag
+/// combinations.
+fn sighash_type_from_u32(raw: u32) -> Result<SigHashType, encode::Error> {
+    let rv = SigHashType::from_u32(raw);
 
-        for cnum in self.1.iter() {
-            rv.append(&mut serialize(&u32::from(cnum.clone())))
+    if rv.as_u32() == raw {
+        Ok(rv)
+    } else {
+        Err(encode::Error::ParseFailed("non-standard sighash type"))
+    }
+}
+
+impl Deserialize for SigHashType {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let raw: u32 = encode::deserialize(bytes)?;
+        sighash_type_from_u32(raw)
+    }
+
+    fn consensus_decode<R: io::Read>(reader: R) -> Result<Self, encode::Error> {
+        let raw: u32 = Decodable::consensus_decode(reader)?;
+        sighash_type_from_u32(raw)
+    }
+}
+
+impl Serialize for KeySource {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = self.0.to_bytes().to_vec();
+
+        for cnum in self.1.into_iter() {
+            buf.extend(encode::serialize(&u32::from(*cnum)));
         }
 
-        rv
+        buf
     }
 }
 
-impl Deserialize for (Fingerprint, Vec<ChildNumber>) {
+impl Deserialize for KeySource {
     fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
         if bytes.len() < 4 {
             return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
         }
 
         let fprint: Fingerprint = Fingerprint::from(&bytes[0..4]);
+
+        if (bytes.len() - 4) % 4 != 0 {
+            return Err(encode::Error::ParseFailed("key derivation path bytes must be a multiple of 4"))
+        }
+
         let mut dpath: Vec<ChildNumber> = Default::default();
 
         let d = &mut Cursor::new(&bytes[4..]);
-        loop {
-            match Decodable::consensus_decode(d) {
-                Ok(index) => {
-                    dpath.push(<ChildNumber as From<u32>>::from(index));
-
-                    if d.position() == (bytes.len() - 4) as u64 {
-                        break;
-                    }
-                },
-                Err(e) => return Err(e),
-            }
+        while d.position() < (bytes.len() - 4) as u64 {
+            let index: u32 = Decodable::consensus_decode(&mut *d)?;
+            dpath.push(ChildNumber::from(index));
+        }
+
+        Ok((fprint, DerivationPath::from(dpath)))
+    }
+}
+
+impl Serialize for XOnlyPublicKey {
+    fn serialize(&self) -> Vec<u8> {
+        XOnlyPublicKey::serialize(self).to_vec()
+    }
+
+    fn consensus_encode<W: io::Write>(&self, mut writer: W) -> Result<usize, io::Error> {
+        let bytes = XOnlyPublicKey::serialize(self);
+        writer.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl Deserialize for XOnlyPublicKey {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        XOnlyPublicKey::from_slice(bytes)
+            .map_err(|_| encode::Error::ParseFailed("invalid x-only public key"))
+    }
+
+    fn consensus_decode<R: io::Read>(mut reader: R) -> Result<Self, encode::Error> {
+        let mut buf = [0u8; 32];
+        reader.read_exact(&mut buf)?;
+        XOnlyPublicKey::from_slice(&buf)
+            .map_err(|_| encode::Error::ParseFailed("invalid x-only public key"))
+    }
+}
+
+impl Serialize for SchnorrSig {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = self.sig.to_vec();
+
+        if self.hash_ty != SchnorrSigHashType::DEFAULT {
+            buf.push(self.hash_ty.as_u8());
+        }
+
+        buf
+    }
+}
+
+impl Deserialize for SchnorrSig {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let (sig_bytes, hash_ty) = match bytes.len() {
+            64 => (bytes, SchnorrSigHashType::DEFAULT),
+            65 => (&bytes[..64], SchnorrSigHashType::from_u8(bytes[64])),
+            _ => return Err(encode::Error::ParseFailed("invalid Schnorr signature length")),
+        };
+
+        let mut sig = [0u8; 64];
+        sig.copy_from_slice(sig_bytes);
+
+        Ok(SchnorrSig { sig, hash_ty })
+    }
+}
+
+impl Serialize for TapLeafHash {
+    fn serialize(&self) -> Vec<u8> {
+        self.into_inner().to_vec()
+    }
+
+    fn consensus_encode<W: io::Write>(&self, mut writer: W) -> Result<usize, io::Error> {
+        let bytes = self.into_inner();
+        writer.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl Deserialize for TapLeafHash {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        if bytes.len() != 32 {
+            return Err(encode::Error::ParseFailed("invalid length for tap leaf hash"))
+        }
+
+        let mut inner = [0u8; 32];
+        inner.copy_from_slice(bytes);
+        Ok(TapLeafHash::from_inner(inner))
+    }
+
+    fn consensus_decode<R: io::Read>(mut reader: R) -> Result<Self, encode::Error> {
+        let mut inner = [0u8; 32];
+        reader.read_exact(&mut inner)?;
+        Ok(TapLeafHash::from_inner(inner))
+    }
+}
+
+impl Serialize for (Vec<TapLeafHash>, KeySource) {
+    fn serialize(&self) -> Vec<u8> {
+        let (leaf_hashes, key_source) = self;
+        let mut buf = encode::serialize(&encode::VarInt(leaf_hashes.len() as u64));
+
+        for hash in leaf_hashes {
+            buf.extend(hash.serialize());
+        }
+
+        buf.extend(key_source.serialize());
+
+        buf
+    }
+}
+
+impl Deserialize for (Vec<TapLeafHash>, KeySource) {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let mut decoder = Cursor::new(bytes);
+        let rv = Self::consensus_decode(&mut decoder)?;
+
+        if decoder.position() as usize == bytes.len() {
+            Ok(rv)
+        } else {
+            Err(encode::Error::ParseFailed("data not consumed entirely when explicitly deserializing"))
         }
+    }
+
+    fn consensus_decode<R: io::Read>(mut reader: R) -> Result<Self, encode::Error> {
+        let num_hashes = encode::VarInt::consensus_decode(&mut reader)?.0 as usize;
+
+        // Bound the claimed count against the same ceiling the control block enforces on its
+        // merkle branch before reserving capacity for it; nothing has validated this varint yet.
+        if num_hashes > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(encode::Error::ParseFailed("too many tap leaf hashes in taproot key source"))
+        }
+
+        let mut leaf_hashes = Vec::with_capacity(num_hashes);
+        for _ in 0..num_hashes {
+            leaf_hashes.push(TapLeafHash::consensus_decode(&mut reader)?);
+        }
+
+        let key_source = KeySource::consensus_decode(&mut reader)?;
+
+        Ok((leaf_hashes, key_source))
+    }
+}
+
+impl Serialize for ControlBlock {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![self.leaf_version.as_u8() | (self.output_key_parity as u8)];
+
+        buf.extend(self.internal_key.serialize());
+
+        for node in &self.merkle_branch {
+            buf.extend(node);
+        }
+
+        buf
+    }
+}
+
+impl Deserialize for ControlBlock {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        if bytes.len() < TAPROOT_CONTROL_BASE_SIZE {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+        }
+
+        let remaining = bytes.len() - TAPROOT_CONTROL_BASE_SIZE;
+        if remaining % TAPROOT_CONTROL_NODE_SIZE != 0 {
+            return Err(encode::Error::ParseFailed("control block merkle branch is not 32-byte aligned"))
+        }
+        if remaining / TAPROOT_CONTROL_NODE_SIZE > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(encode::Error::ParseFailed("control block merkle branch is too long"))
+        }
+
+        let output_key_parity = bytes[0] & 1 == 1;
+        let leaf_version = LeafVersion::from_u8(bytes[0]);
+        let internal_key = XOnlyPublicKey::deserialize(&bytes[1..TAPROOT_CONTROL_BASE_SIZE])?;
+
+        let merkle_branch = bytes[TAPROOT_CONTROL_BASE_SIZE..]
+            .chunks(TAPROOT_CONTROL_NODE_SIZE)
+            .map(|chunk| {
+                let mut node = [0u8; 32];
+                node.copy_from_slice(chunk);
+                node
+            })
+            .collect();
+
+        Ok(ControlBlock { leaf_version, output_key_parity, internal_key, merkle_branch })
+    }
+}
+
+impl Serialize for Vec<u8> {
+    fn serialize(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl Deserialize for Vec<u8> {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Serialize for ProprietaryKey {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = encode::serialize(&self.prefix);
+
+        buf.extend(encode::serialize(&encode::VarInt(self.subtype as u64)));
+        buf.extend(&self.key);
+
+        buf
+    }
+}
+
+impl Deserialize for ProprietaryKey {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let mut decoder = Cursor::new(bytes);
+
+        let prefix: Vec<u8> = Decodable::consensus_decode(&mut decoder)?;
+
+        // BIP174 defines the subtype as a compact-size integer; it happens to be stored here as
+        // `u8`, so reject subtypes that wouldn't round-trip through that representation.
+        let subtype = encode::VarInt::consensus_decode(&mut decoder)?.0;
+        let subtype = u8::try_from(subtype)
+            .map_err(|_| encode::Error::ParseFailed("proprietary key subtype out of range"))?;
+
+        let mut key = Vec::new();
+        decoder.read_to_end(&mut key)?;
+
+        Ok(ProprietaryKey { prefix, subtype, key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn sighash_type_roundtrips() {
+        let sht = SigHashType::from_u32(0x01); // SIGHASH_ALL
+        let ser = sht.serialize();
+        assert_eq!(SigHashType::deserialize(&ser).unwrap(), sht);
+    }
+
+    #[test]
+    fn sighash_type_rejects_bad_length() {
+        assert!(SigHashType::deserialize(&[0x01, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn sighash_type_rejects_non_standard_flags() {
+        assert!(SigHashType::deserialize(&[0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn txout_roundtrips() {
+        let txout = TxOut { value: 1_000_000, script_pubkey: Script::new() };
+        let ser = txout.serialize();
+        assert_eq!(TxOut::deserialize(&ser).unwrap(), txout);
+    }
+
+    #[test]
+    fn proprietary_key_roundtrips() {
+        let key = ProprietaryKey { prefix: b"ADSIG".to_vec(), subtype: 0x00, key: vec![0xde, 0xad, 0xbe, 0xef] };
+        let ser = key.serialize();
+        assert_eq!(ProprietaryKey::deserialize(&ser).unwrap(), key);
+    }
+
+    #[test]
+    fn proprietary_value_roundtrips() {
+        let value = vec![0x01, 0x02, 0x03];
+        let ser = Serialize::serialize(&value);
+        assert_eq!(ser, value);
+        assert_eq!(<Vec<u8> as Deserialize>::deserialize(&ser).unwrap(), value);
+    }
+
+    #[test]
+    fn key_source_roundtrips() {
+        let path = DerivationPath::from_str("m/84'/0'/0'/0/5").unwrap();
+        let key_source: KeySource = (Fingerprint::from(&[0xd3, 0x4d, 0xb3, 0x3f][..]), path);
+        let ser = key_source.serialize();
+        assert_eq!(KeySource::deserialize(&ser).unwrap(), key_source);
+    }
+
+    #[test]
+    fn key_source_rejects_misaligned_derivation_path() {
+        // 4 bytes of fingerprint plus a derivation path that isn't a whole number of 4-byte
+        // indexes.
+        assert!(KeySource::deserialize(&[0xd3, 0x4d, 0xb3, 0x3f, 0x00, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn derivation_path_roundtrips_through_str() {
+        let path = "m/84'/0'/0'/0/5";
+        assert_eq!(DerivationPath::from_str(path).unwrap().to_string(), path);
+    }
+
+    #[test]
+    fn x_only_public_key_roundtrips() {
+        let key = XOnlyPublicKey::from_slice(&[0x01; 32]).unwrap();
+        let ser = key.serialize();
+        assert_eq!(XOnlyPublicKey::deserialize(&ser).unwrap(), key);
+    }
+
+    #[test]
+    fn schnorr_sig_roundtrips_with_and_without_hash_type() {
+        let default_sig = SchnorrSig { sig: [0x02; 64], hash_ty: SchnorrSigHashType::DEFAULT };
+        let ser = default_sig.serialize();
+        assert_eq!(ser.len(), 64);
+        assert_eq!(SchnorrSig::deserialize(&ser).unwrap(), default_sig);
+
+        let tagged_sig = SchnorrSig { sig: [0x03; 64], hash_ty: SchnorrSigHashType::from_u8(0x01) };
+        let ser = tagged_sig.serialize();
+        assert_eq!(ser.len(), 65);
+        assert_eq!(SchnorrSig::deserialize(&ser).unwrap(), tagged_sig);
+    }
+
+    #[test]
+    fn tap_leaf_hash_roundtrips() {
+        let hash = TapLeafHash::from_inner([0x04; 32]);
+        let ser = hash.serialize();
+        assert_eq!(TapLeafHash::deserialize(&ser).unwrap(), hash);
+    }
+
+    #[test]
+    fn tap_leaf_hashes_and_key_source_roundtrip() {
+        let path = DerivationPath::from_str("m/86'/0'/0'").unwrap();
+        let key_source: KeySource = (Fingerprint::from(&[0xaa, 0xbb, 0xcc, 0xdd][..]), path);
+        let value = (vec![TapLeafHash::from_inner([0x05; 32]), TapLeafHash::from_inner([0x06; 32])], key_source);
+        let ser = Serialize::serialize(&value);
+        assert_eq!(<(Vec<TapLeafHash>, KeySource) as Deserialize>::deserialize(&ser).unwrap(), value);
+    }
+
+    #[test]
+    fn tap_leaf_hashes_rejects_count_over_merkle_bound() {
+        let mut buf = encode::serialize(&encode::VarInt((TAPROOT_CONTROL_MAX_NODE_COUNT + 1) as u64));
+        buf.extend(vec![0u8; 32 * (TAPROOT_CONTROL_MAX_NODE_COUNT + 1)]);
+        buf.extend(KeySource::serialize(&(Fingerprint::default(), DerivationPath::default())));
+        assert!(<(Vec<TapLeafHash>, KeySource) as Deserialize>::deserialize(&buf).is_err());
+    }
+
+    #[test]
+    fn control_block_roundtrips() {
+        let block = ControlBlock {
+            leaf_version: LeafVersion::TAPSCRIPT,
+            output_key_parity: true,
+            internal_key: XOnlyPublicKey::from_slice(&[0x07; 32]).unwrap(),
+            merkle_branch: vec![[0x08; 32], [0x09; 32]],
+        };
+        let ser = block.serialize();
+        assert_eq!(ControlBlock::deserialize(&ser).unwrap(), block);
+    }
+
+    #[test]
+    fn control_block_rejects_misaligned_merkle_branch() {
+        let mut ser = vec![LeafVersion::TAPSCRIPT.as_u8(); TAPROOT_CONTROL_BASE_SIZE];
+        ser.extend(vec![0u8; 1]); // one stray byte, not a whole 32-byte node
+        assert!(ControlBlock::deserialize(&ser).is_err());
+    }
+
+    #[test]
+    fn control_block_rejects_merkle_branch_over_bound() {
+        let mut ser = vec![LeafVersion::TAPSCRIPT.as_u8(); TAPROOT_CONTROL_BASE_SIZE];
+        ser.extend(vec![0u8; TAPROOT_CONTROL_NODE_SIZE * (TAPROOT_CONTROL_MAX_NODE_COUNT + 1)]);
+        assert!(ControlBlock::deserialize(&ser).is_err());
+    }
+
+    #[test]
+    fn proprietary_key_survives_unknown_trailing_key_bytes() {
+        // An application-defined `key` tail with no further structure must survive a
+        // parse/serialize round trip untouched, even though this module has no idea what it
+        // means.
+        let key = ProprietaryKey { prefix: b"XYZ".to_vec(), subtype: 0x07, key: vec![0x01, 0x02, 0x03] };
+        let ser = key.serialize();
+        assert_eq!(ProprietaryKey::deserialize(&ser).unwrap(), key);
+    }
+
+    #[test]
+    fn proprietary_key_subtype_encodes_as_varint() {
+        // BIP174 subtype values above 252 need the 2-byte VarInt marker (0xfd) rather than a
+        // single raw byte, the point at which the two encodings diverge.
+        let key = ProprietaryKey { prefix: b"XYZ".to_vec(), subtype: 254, key: vec![0xaa] };
+        let ser = key.serialize();
+
+        let prefix_len = encode::VarInt::consensus_decode(&mut Cursor::new(&ser)).unwrap().0 as usize;
+        let subtype_offset = encode::serialize(&encode::VarInt(prefix_len as u64)).len() + prefix_len;
+        assert_eq!(ser[subtype_offset], 0xfd);
+        assert_eq!(&ser[subtype_offset + 1..subtype_offset + 3], &[254, 0x00]);
 
-        Ok((fprint, dpath))
+        assert_eq!(ProprietaryKey::deserialize(&ser).unwrap(), key);
     }
 }
@@ -0,0 +1,63 @@
+//! BIP340 Schnorr-related types used by Taproot.
+
+/// A BIP340 x-only public key, used as the internal/output key in Taproot outputs and key-source
+/// entries.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct XOnlyPublicKey([u8; 32]);
+
+impl XOnlyPublicKey {
+    /// Creates an x-only public key directly from a 32-byte slice.
+    pub fn from_slice(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != 32 {
+            return Err(Error::InvalidXOnlyPublicKey);
+        }
+
+        let mut buf = [0u8; 32];
+        buf.copy_from_slice(data);
+        Ok(XOnlyPublicKey(buf))
+    }
+
+    /// Returns the 32-byte BIP340 serialization of this key.
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+/// A BIP341 sighash type suffix on a Schnorr signature.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SchnorrSigHashType(u8);
+
+impl SchnorrSigHashType {
+    /// `SIGHASH_DEFAULT` — the implicit sighash type used when a Schnorr signature carries no
+    /// explicit suffix byte.
+    pub const DEFAULT: SchnorrSigHashType = SchnorrSigHashType(0);
+
+    /// Creates a sighash type from its raw byte value.
+    pub fn from_u8(byte: u8) -> SchnorrSigHashType {
+        SchnorrSigHashType(byte)
+    }
+
+    /// Returns the raw byte value of this sighash type.
+    pub fn as_u8(&self) -> u8 {
+        self.0
+    }
+}
+
+/// A Schnorr signature, with an optional (defaulting to [`SchnorrSigHashType::DEFAULT`]) sighash
+/// type suffix as used in Taproot key-path and script-path spends.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SchnorrSig {
+    /// The underlying 64-byte BIP340 signature.
+    pub sig: [u8; 64],
+    /// The sighash type carried alongside the signature.
+    pub hash_ty: SchnorrSigHashType,
+}
+
+/// An error constructing a Schnorr-related Taproot type.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Raw bytes are not a valid 32-byte x-only public key.
+    InvalidXOnlyPublicKey,
+    /// Raw bytes are not a valid 64- or 65-byte Schnorr signature.
+    InvalidSchnorrSig,
+}
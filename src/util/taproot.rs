@@ -0,0 +1,63 @@
+//! Taproot (BIP 341) types shared by script-path spending and PSBT (BIP 371) serialization.
+
+use util::schnorr::XOnlyPublicKey;
+
+/// The size, in bytes, of a leaf-version-and-parity byte plus an internal key, i.e. the fixed
+/// prefix of every control block before the variable-length merkle branch.
+pub const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+/// The size, in bytes, of a single merkle branch node in a control block.
+pub const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+/// The maximum number of merkle branch nodes a control block may contain.
+pub const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+/// The maximum possible size, in bytes, of a control block.
+pub const TAPROOT_CONTROL_MAX_SIZE: usize =
+    TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * TAPROOT_CONTROL_MAX_NODE_COUNT;
+
+/// A BIP341 `TapLeaf` tagged hash, identifying a single leaf script in a taproot tree.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct TapLeafHash([u8; 32]);
+
+impl TapLeafHash {
+    /// Wraps a raw 32-byte hash value.
+    pub fn from_inner(inner: [u8; 32]) -> TapLeafHash {
+        TapLeafHash(inner)
+    }
+
+    /// Returns the raw bytes of this hash.
+    pub fn into_inner(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+/// A BIP342 leaf version, the first byte of a control block (with the parity bit masked out).
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LeafVersion(u8);
+
+impl LeafVersion {
+    /// The initial, and so far only, leaf version: `0xc0`, used for tapscript.
+    pub const TAPSCRIPT: LeafVersion = LeafVersion(0xc0);
+
+    /// Creates a leaf version from its raw byte, masking out the output-key-parity bit.
+    pub fn from_u8(version: u8) -> LeafVersion {
+        LeafVersion(version & 0xfe)
+    }
+
+    /// Returns the raw byte value of this leaf version.
+    pub fn as_u8(&self) -> u8 {
+        self.0
+    }
+}
+
+/// A control block, proving that a tapscript leaf is committed to by a taproot output key, as
+/// defined by BIP 341.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ControlBlock {
+    /// The tapscript leaf version.
+    pub leaf_version: LeafVersion,
+    /// Whether the output key has an odd Y coordinate.
+    pub output_key_parity: bool,
+    /// The internal key committed to by the output key.
+    pub internal_key: XOnlyPublicKey,
+    /// The merkle branch from the leaf to the root of the taproot tree, innermost node first.
+    pub merkle_branch: Vec<[u8; 32]>,
+}